@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Size of a SHA-256 digest.
+pub const HASH_LEN: usize = 32;
+
+/// Content address of a value: the SHA-256 digest of its bytes. Two keys
+/// whose values hash to the same `ValueHash` can share a single stored blob.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ValueHash([u8; HASH_LEN]);
+
+impl ValueHash {
+    /// Hashes `value` with SHA-256 to produce its content address.
+    pub fn of(value: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(value);
+        ValueHash(hasher.finalize().into())
+    }
+}
+
+impl AsRef<[u8]> for ValueHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ValueHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Location of a stored blob (mirrors `BitcaskKeyFile`'s `KeyMetadata`).
+/// How many keys point at it is tracked separately, by [`ContentStore`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BlobLocation {
+    pub file_id: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BlobEntry {
+    location: BlobLocation,
+    ref_count: u32,
+}
+
+/// Index of content-addressed blobs, keyed by the SHA-256 hash of their
+/// value bytes. A key that writes a value already present here is pointed
+/// at the existing blob instead of storing another copy; `release` is
+/// called when a key that pointed at a blob is overwritten or removed, so a
+/// blob's ref count reaches zero once no key written through the dedup path
+/// references it any more and the next compaction can omit it from the
+/// rewritten content file. `merge` itself never calls `release`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ContentStore {
+    blobs: HashMap<ValueHash, BlobEntry>,
+}
+
+impl ContentStore {
+    pub fn new() -> Self {
+        ContentStore {
+            blobs: HashMap::new(),
+        }
+    }
+
+    /// If `hash` already has a stored blob, registers another reference to
+    /// it and returns its location — the caller should skip writing the
+    /// value and point the key at this location instead. Returns `None` if
+    /// this is a new value: the caller must write it and then call
+    /// [`ContentStore::insert`] with its resulting location.
+    pub fn acquire(&mut self, hash: ValueHash) -> Option<BlobLocation> {
+        let entry = self.blobs.get_mut(&hash)?;
+        entry.ref_count += 1;
+        Some(entry.location)
+    }
+
+    /// Records a freshly written blob for `hash` at `location` with an
+    /// initial reference count of one. Call only after [`ContentStore::acquire`]
+    /// returned `None` for the same hash.
+    pub fn insert(&mut self, hash: ValueHash, location: BlobLocation) {
+        self.blobs.insert(
+            hash,
+            BlobEntry {
+                location,
+                ref_count: 1,
+            },
+        );
+    }
+
+    /// Drops one reference to `hash`'s blob. Returns `true` if that was the
+    /// last reference, meaning the blob is now dead and a merge can drop it
+    /// from the rewritten content file.
+    pub fn release(&mut self, hash: ValueHash) -> bool {
+        let Some(entry) = self.blobs.get_mut(&hash) else {
+            return false;
+        };
+        entry.ref_count = entry.ref_count.saturating_sub(1);
+        if entry.ref_count == 0 {
+            self.blobs.remove(&hash);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Current reference count for `hash`'s blob, or zero if it isn't known.
+    pub fn ref_count(&self, hash: ValueHash) -> u32 {
+        self.blobs.get(&hash).map_or(0, |entry| entry.ref_count)
+    }
+
+    /// Points `hash`'s existing entry at `location`, leaving its reference
+    /// count untouched. Call this after a `merge` rewrites the blob into a
+    /// new file, so a later `acquire` hands out the new location instead of
+    /// a dangling one into a file `merge` has since deleted. A no-op if
+    /// `hash` has no entry (nothing currently references it).
+    pub fn relocate(&mut self, hash: ValueHash, location: BlobLocation) {
+        if let Some(entry) = self.blobs.get_mut(&hash) {
+            entry.location = location;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_hash_is_stable_and_content_sensitive() {
+        assert_eq!(ValueHash::of(b"hello"), ValueHash::of(b"hello"));
+        assert_ne!(ValueHash::of(b"hello"), ValueHash::of(b"world"));
+    }
+
+    #[test]
+    fn test_value_hash_debug_is_hex() {
+        let hash = ValueHash::of(b"");
+        let rendered = format!("{hash:?}");
+        assert_eq!(rendered.len(), HASH_LEN * 2);
+        assert!(rendered.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_acquire_misses_until_inserted() {
+        let mut store = ContentStore::new();
+        let hash = ValueHash::of(b"payload");
+
+        assert!(store.acquire(hash).is_none());
+
+        let location = BlobLocation {
+            file_id: 0,
+            offset: 10,
+            size: 7,
+        };
+        store.insert(hash, location);
+
+        let acquired = store.acquire(hash).unwrap();
+        assert_eq!(acquired.offset, location.offset);
+        assert_eq!(store.ref_count(hash), 2);
+    }
+
+    #[test]
+    fn test_release_drops_blob_at_zero_refs() {
+        let mut store = ContentStore::new();
+        let hash = ValueHash::of(b"payload");
+        store.insert(
+            hash,
+            BlobLocation {
+                file_id: 0,
+                offset: 0,
+                size: 7,
+            },
+        );
+        store.acquire(hash); // second reference, ref_count now 2
+
+        assert!(!store.release(hash));
+        assert_eq!(store.ref_count(hash), 1);
+        assert!(store.release(hash));
+        assert_eq!(store.ref_count(hash), 0);
+    }
+
+    #[test]
+    fn test_relocate_updates_location_without_changing_ref_count() {
+        let mut store = ContentStore::new();
+        let hash = ValueHash::of(b"payload");
+        store.insert(
+            hash,
+            BlobLocation {
+                file_id: 0,
+                offset: 0,
+                size: 7,
+            },
+        );
+        store.acquire(hash); // second reference, ref_count now 2
+
+        let new_location = BlobLocation {
+            file_id: 1,
+            offset: 42,
+            size: 7,
+        };
+        store.relocate(hash, new_location);
+
+        let acquired = store.acquire(hash).unwrap();
+        assert_eq!(acquired.file_id, new_location.file_id);
+        assert_eq!(acquired.offset, new_location.offset);
+        assert_eq!(store.ref_count(hash), 3);
+    }
+
+    #[test]
+    fn test_relocate_is_noop_for_unknown_hash() {
+        let mut store = ContentStore::new();
+        let hash = ValueHash::of(b"never inserted");
+
+        store.relocate(
+            hash,
+            BlobLocation {
+                file_id: 0,
+                offset: 0,
+                size: 0,
+            },
+        );
+
+        assert!(store.acquire(hash).is_none());
+    }
+}
@@ -0,0 +1,240 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce as AesGcmNonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce as ChaChaNonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// AEAD nonce size used by both supported ciphers (96 bits).
+pub const NONCE_LEN: usize = 12;
+/// Size of the random salt generated per data file for Argon2.
+pub const SALT_LEN: usize = 16;
+/// Size of the derived key, required by both AES-256-GCM and ChaCha20-Poly1305.
+pub const KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("failed to derive key from passphrase: {0}")]
+    KeyDerivation(String),
+    #[error("encryption failed: {0}")]
+    Encrypt(String),
+    #[error("decryption failed: {0}")]
+    Decrypt(String),
+    #[error("unknown encryption algorithm id {0}")]
+    UnknownAlgorithm(u8),
+}
+
+/// AEAD cipher used to protect record payloads at rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EncryptionType {
+    AesGcm,
+    ChaCha20Poly1305,
+}
+
+impl EncryptionType {
+    fn id(self) -> u8 {
+        match self {
+            EncryptionType::AesGcm => 0,
+            EncryptionType::ChaCha20Poly1305 => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, EncryptionError> {
+        match id {
+            0 => Ok(EncryptionType::AesGcm),
+            1 => Ok(EncryptionType::ChaCha20Poly1305),
+            other => Err(EncryptionError::UnknownAlgorithm(other)),
+        }
+    }
+}
+
+/// Encryption parameters persisted once per data file so the store can be
+/// reopened with only the passphrase: which AEAD cipher was used, the random
+/// salt fed into Argon2, and the Argon2 cost parameters the key was derived
+/// with.
+#[derive(Debug, Clone)]
+pub struct EncryptionHeader {
+    pub algorithm: EncryptionType,
+    pub salt: [u8; SALT_LEN],
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl EncryptionHeader {
+    /// Generates a fresh header for `algorithm` with a random salt and the
+    /// Argon2 default cost parameters.
+    pub fn new(algorithm: EncryptionType) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        EncryptionHeader {
+            algorithm,
+            salt,
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+
+    /// Derives the 256-bit data key from `passphrase` using this header's
+    /// salt and Argon2 cost parameters.
+    pub fn derive_key(&self, passphrase: &str) -> Result<[u8; KEY_LEN], EncryptionError> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_LEN))
+            .map_err(|e| EncryptionError::KeyDerivation(e.to_string()))?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+        let mut key = [0u8; KEY_LEN];
+        argon2
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+            .map_err(|e| EncryptionError::KeyDerivation(e.to_string()))?;
+
+        Ok(key)
+    }
+
+    /// Serializes this header into the fixed layout persisted at the start
+    /// of an encrypted data file: `[algorithm id][salt][m_cost][t_cost][p_cost]`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + SALT_LEN + 12);
+        buf.push(self.algorithm.id());
+        buf.extend(self.salt);
+        buf.extend(self.m_cost.to_be_bytes());
+        buf.extend(self.t_cost.to_be_bytes());
+        buf.extend(self.p_cost.to_be_bytes());
+        buf
+    }
+
+    pub fn from_bytes(input: &[u8]) -> Result<Self, EncryptionError> {
+        if input.len() < 1 + SALT_LEN + 12 {
+            return Err(EncryptionError::KeyDerivation(String::from(
+                "encryption header is truncated",
+            )));
+        }
+
+        let algorithm = EncryptionType::from_id(input[0])?;
+
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&input[1..1 + SALT_LEN]);
+
+        let cost_at = |offset: usize| {
+            u32::from_be_bytes(input[offset..offset + 4].try_into().expect("checked length"))
+        };
+        let m_cost = cost_at(1 + SALT_LEN);
+        let t_cost = cost_at(1 + SALT_LEN + 4);
+        let p_cost = cost_at(1 + SALT_LEN + 8);
+
+        Ok(EncryptionHeader {
+            algorithm,
+            salt,
+            m_cost,
+            t_cost,
+            p_cost,
+        })
+    }
+}
+
+/// A derived-key AEAD cipher used to encrypt/decrypt individual records.
+pub struct Cipher {
+    encryption_type: EncryptionType,
+    key: [u8; KEY_LEN],
+}
+
+impl Cipher {
+    pub fn new(encryption_type: EncryptionType, key: [u8; KEY_LEN]) -> Self {
+        Cipher {
+            encryption_type,
+            key,
+        }
+    }
+
+    /// Encrypts `plaintext` under a freshly generated random nonce, returning
+    /// `(nonce, ciphertext_with_tag)`.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>), EncryptionError> {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce_bytes);
+
+        let ciphertext = match self.encryption_type {
+            EncryptionType::AesGcm => {
+                let cipher = Aes256Gcm::new_from_slice(&self.key)
+                    .map_err(|e| EncryptionError::Encrypt(e.to_string()))?;
+                cipher
+                    .encrypt(AesGcmNonce::from_slice(&nonce_bytes), plaintext)
+                    .map_err(|e| EncryptionError::Encrypt(e.to_string()))?
+            }
+            EncryptionType::ChaCha20Poly1305 => {
+                let cipher = ChaCha20Poly1305::new_from_slice(&self.key)
+                    .map_err(|e| EncryptionError::Encrypt(e.to_string()))?;
+                cipher
+                    .encrypt(ChaChaNonce::from_slice(&nonce_bytes), plaintext)
+                    .map_err(|e| EncryptionError::Encrypt(e.to_string()))?
+            }
+        };
+
+        Ok((nonce_bytes.to_vec(), ciphertext))
+    }
+
+    /// Decrypts `ciphertext` (with its authentication tag appended) using
+    /// `nonce`, returning the plaintext.
+    pub fn decrypt(&self, nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        match self.encryption_type {
+            EncryptionType::AesGcm => {
+                let cipher = Aes256Gcm::new_from_slice(&self.key)
+                    .map_err(|e| EncryptionError::Decrypt(e.to_string()))?;
+                cipher
+                    .decrypt(AesGcmNonce::from_slice(nonce), ciphertext)
+                    .map_err(|e| EncryptionError::Decrypt(e.to_string()))
+            }
+            EncryptionType::ChaCha20Poly1305 => {
+                let cipher = ChaCha20Poly1305::new_from_slice(&self.key)
+                    .map_err(|e| EncryptionError::Decrypt(e.to_string()))?;
+                cipher
+                    .decrypt(ChaChaNonce::from_slice(nonce), ciphertext)
+                    .map_err(|e| EncryptionError::Decrypt(e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encryption_header_roundtrip_bytes() {
+        let header = EncryptionHeader::new(EncryptionType::ChaCha20Poly1305);
+        let bytes = header.to_bytes();
+        let parsed = EncryptionHeader::from_bytes(&bytes).unwrap();
+
+        assert_eq!(parsed.algorithm, header.algorithm);
+        assert_eq!(parsed.salt, header.salt);
+        assert_eq!(parsed.m_cost, header.m_cost);
+        assert_eq!(parsed.t_cost, header.t_cost);
+        assert_eq!(parsed.p_cost, header.p_cost);
+    }
+
+    #[test]
+    fn test_cipher_roundtrip_for_each_algorithm() {
+        for algorithm in [EncryptionType::AesGcm, EncryptionType::ChaCha20Poly1305] {
+            let header = EncryptionHeader::new(algorithm);
+            let key = header.derive_key("hunter2").unwrap();
+            let cipher = Cipher::new(algorithm, key);
+
+            let (nonce, ciphertext) = cipher.encrypt(b"super secret value").unwrap();
+            let plaintext = cipher.decrypt(&nonce, &ciphertext).unwrap();
+
+            assert_eq!(plaintext, b"super secret value");
+        }
+    }
+
+    #[test]
+    fn test_decrypt_fails_with_wrong_key() {
+        let header = EncryptionHeader::new(EncryptionType::AesGcm);
+        let cipher = Cipher::new(EncryptionType::AesGcm, header.derive_key("correct").unwrap());
+        let (nonce, ciphertext) = cipher.encrypt(b"payload").unwrap();
+
+        let wrong_cipher = Cipher::new(EncryptionType::AesGcm, header.derive_key("wrong").unwrap());
+        assert!(wrong_cipher.decrypt(&nonce, &ciphertext).is_err());
+    }
+}
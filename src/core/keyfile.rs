@@ -1,12 +1,21 @@
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use bincode::{deserialize, serialize};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::core::content_store::{BlobLocation, ContentStore, ValueHash};
+use crate::core::serdes::{record_len, DeserializeErrorKind, Key, KeyValue, Serdes};
+
+/// Default fraction of known record bytes, across all data files, that must
+/// be dead (tombstoned or superseded) before [`BitcaskKeyFile::needs_merge`]
+/// recommends compaction, for callers to pass as its `threshold`.
+pub const DEFAULT_MERGE_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 struct KeyMetadata {
     file_id: u32,
     offset: u64,
@@ -16,6 +25,18 @@ struct KeyMetadata {
 pub struct BitcaskKeyFile {
     file_path: String,
     key_map: HashMap<String, KeyMetadata>,
+    /// Bytes occupied by records that are still the live value for their
+    /// key, across all known data files.
+    live_bytes: u64,
+    /// Bytes occupied by records that are no longer reachable from the
+    /// keydir (superseded values and tombstones) — reclaimable by `merge`.
+    dead_bytes: u64,
+    /// Blob index backing [`BitcaskKeyFile::add_key_deduped`]; tracks how
+    /// many live keys reference each content-addressed value.
+    content_store: ContentStore,
+    /// Hash each content-addressed key currently points at, so a later
+    /// overwrite or delete can release its old blob reference.
+    key_hashes: HashMap<String, ValueHash>,
 }
 
 impl BitcaskKeyFile {
@@ -23,6 +44,10 @@ impl BitcaskKeyFile {
         BitcaskKeyFile {
             file_path: file_path.to_string(),
             key_map: HashMap::new(),
+            live_bytes: 0,
+            dead_bytes: 0,
+            content_store: ContentStore::new(),
+            key_hashes: HashMap::new(),
         }
     }
 
@@ -33,7 +58,16 @@ impl BitcaskKeyFile {
             let mut buf = Vec::new();
             buf_reader.read_to_end(&mut buf)?;
 
-            self.key_map = deserialize(&buf)?;
+            let (key_map, content_store, key_hashes) = deserialize(&buf)?;
+            self.key_map = key_map;
+            self.content_store = content_store;
+            self.key_hashes = key_hashes;
+            // live_bytes/dead_bytes aren't part of the hint file, so rebuild
+            // live_bytes from what was actually loaded rather than leaving it
+            // at the `new()` default of zero — otherwise the first call to
+            // `add_key`/`add_key_deduped` for a key that was already in the
+            // hint file underflows it.
+            self.live_bytes = self.key_map.values().map(|meta| meta.size).sum();
         }
 
         Ok(())
@@ -47,14 +81,14 @@ impl BitcaskKeyFile {
             .open(&self.file_path)?;
 
         let mut buf_writer = BufWriter::new(file);
-        let buf = serialize(&self.key_map)?;
+        let buf = serialize(&(&self.key_map, &self.content_store, &self.key_hashes))?;
         buf_writer.write_all(&buf)?;
 
         Ok(())
     }
 
     pub fn add_key(&mut self, key: String, file_id: u32, offset: u64, size: u64) {
-        self.key_map.insert(
+        let previous = self.key_map.insert(
             key,
             KeyMetadata {
                 file_id,
@@ -62,13 +96,578 @@ impl BitcaskKeyFile {
                 size,
             },
         );
+        if let Some(previous) = previous {
+            // Backstop, not the primary fix: live_bytes should already be in
+            // sync with key_map (see `load`), but a saturating subtraction
+            // here means a bookkeeping drift degrades into an inaccurate
+            // dead_byte_ratio instead of a panic.
+            self.live_bytes = self.live_bytes.saturating_sub(previous.size);
+            self.dead_bytes += previous.size;
+        }
+        self.live_bytes += size;
     }
 
     pub fn get_key_info(&self, key: &str) -> Option<&KeyMetadata> {
         self.key_map.get(key)
     }
 
-    pub fn remove_key(&mut self, key: &str) -> Option<KeyMetadata> {
-        self.key_map.remove(key)
+    /// Like [`BitcaskKeyFile::add_key`], but deduplicates `value` against
+    /// every content-addressed value written through this method that some
+    /// key still references (a blob whose last reference was released is
+    /// forgotten, and writing that value again stores a new copy).
+    /// If `value` hashes the same as one already stored, `key` is pointed at
+    /// the existing blob and no bytes are written; otherwise `value` is
+    /// appended to `data_file_path` and registered as a new blob. Either way
+    /// the keydir ends up with the same `{file_id, offset, size}` shape as a
+    /// normal key, so lookups don't need to know a key was deduplicated.
+    ///
+    /// `data_file_path` should be a content file dedicated to deduped blobs,
+    /// separate from any data file scanned by [`BitcaskKeyFile::recover`] — a
+    /// blob is raw value bytes with none of the CRC/length framing `recover`
+    /// expects of a record. [`BitcaskKeyFile::merge`] is safe to point at a
+    /// content file, since it tracks which keys are content-addressed and
+    /// reads their blobs by the keydir's recorded offset/size rather than by
+    /// parsing a record frame.
+    pub fn add_key_deduped(
+        &mut self,
+        key: String,
+        value: &[u8],
+        data_file_path: &str,
+        file_id: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let hash = ValueHash::of(value);
+
+        // Re-pointing a key at the value it already references shouldn't
+        // acquire a second reference to the same blob.
+        if self.key_hashes.get(&key) == Some(&hash) {
+            return Ok(());
+        }
+
+        let location = match self.content_store.acquire(hash) {
+            Some(location) => location,
+            None => {
+                let mut data_file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .read(true)
+                    .open(data_file_path)?;
+                let offset = data_file.seek(SeekFrom::End(0))?;
+                data_file.write_all(value)?;
+
+                let location = BlobLocation {
+                    file_id,
+                    offset,
+                    size: value.len() as u64,
+                };
+                self.content_store.insert(hash, location);
+                location
+            }
+        };
+
+        if let Some(previous_hash) = self.key_hashes.insert(key.clone(), hash) {
+            self.content_store.release(previous_hash);
+        }
+
+        self.add_key(key, location.file_id, location.offset, location.size);
+        Ok(())
+    }
+
+    /// Deletes `key` by appending a tombstone record to `data_file_path`
+    /// (rather than rewriting any existing file) and dropping `key` from the
+    /// keydir. The tombstone's own bytes, and whatever the key used to point
+    /// at, both become dead weight that a later `merge` can reclaim. If
+    /// `key` was written through [`BitcaskKeyFile::add_key_deduped`], its
+    /// blob reference is released too.
+    pub fn remove_key(
+        &mut self,
+        key: &str,
+        data_file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let tombstone = KeyValue::new_tombstone(key.as_bytes().to_vec());
+        let serialized = KeyValue::serialize(&tombstone)?;
+
+        let mut data_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(data_file_path)?;
+        data_file.write_all(&serialized)?;
+
+        if let Some(previous) = self.key_map.remove(key) {
+            self.live_bytes = self.live_bytes.saturating_sub(previous.size);
+            self.dead_bytes += previous.size;
+        }
+        self.dead_bytes += serialized.len() as u64;
+
+        if let Some(hash) = self.key_hashes.remove(key) {
+            self.content_store.release(hash);
+        }
+
+        Ok(())
+    }
+
+    /// Fraction of known record bytes that are dead (superseded or
+    /// tombstoned) rather than live. Callers compare this against their own
+    /// threshold (see [`DEFAULT_MERGE_THRESHOLD`]) to decide when to merge.
+    pub fn dead_byte_ratio(&self) -> f64 {
+        let total = self.live_bytes + self.dead_bytes;
+        if total == 0 {
+            return 0.0;
+        }
+        self.dead_bytes as f64 / total as f64
+    }
+
+    /// Returns true once `dead_byte_ratio` exceeds `threshold`, meaning a
+    /// `merge` would meaningfully shrink the store.
+    pub fn needs_merge(&self, threshold: f64) -> bool {
+        self.dead_byte_ratio() > threshold
+    }
+
+    /// Rebuilds the keydir by replaying every record in `data_file_path` in
+    /// order, CRC-validating each one and stopping at the first
+    /// torn/truncated tail (the usual sign of a write that was in flight
+    /// when the process crashed). A later record for a key overwrites an
+    /// earlier one, so this ends up pointing at the most recent value for
+    /// every key — the same result `save`'s hint file captures, but derived
+    /// from the data file alone. Call this on startup before relying on the
+    /// hint file, so a missing or stale hint file never loses data.
+    ///
+    /// `data_file_path` must hold only plaintext records written by
+    /// [`Serdes::serialize`]. A record written by `serialize_encrypted`
+    /// doesn't decode as plaintext: depending on its bytes it is reported
+    /// either as malformed, which is surfaced as an error, or as truncated,
+    /// in which case the scan stops there and every later record in the
+    /// file is skipped. Mixing the two formats in a single recover-scanned
+    /// file is not supported.
+    pub fn recover(
+        &mut self,
+        data_file_path: &str,
+        file_id: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !Path::new(data_file_path).exists() {
+            return Ok(());
+        }
+
+        let file = File::open(data_file_path)?;
+        let mut buf_reader = BufReader::new(file);
+        let mut buf = Vec::new();
+        buf_reader.read_to_end(&mut buf)?;
+
+        let mut offset: u64 = 0;
+        while (offset as usize) < buf.len() {
+            let remaining = &buf[offset as usize..];
+
+            let record: KeyValue<Key, _> = match KeyValue::deserialize(remaining) {
+                Ok(record) => record,
+                Err(e) if e.kind == DeserializeErrorKind::Truncated => break,
+                Err(e) => return Err(Box::new(e)),
+            };
+            let size = match record_len(remaining) {
+                Ok(size) => size as u64,
+                Err(e) if e.kind == DeserializeErrorKind::Truncated => break,
+                Err(e) => return Err(Box::new(e)),
+            };
+
+            let key = String::from_utf8_lossy(&record.key).into_owned();
+            if record.is_tombstone || record.is_expired(now_millis()) {
+                if let Some(previous) = self.key_map.remove(&key) {
+                    self.live_bytes = self.live_bytes.saturating_sub(previous.size);
+                    self.dead_bytes += previous.size;
+                }
+                self.dead_bytes += size;
+            } else {
+                self.add_key(key, file_id, offset, size);
+            }
+
+            offset += size;
+        }
+
+        Ok(())
+    }
+
+    /// Compacts `sources` (data files to scan, given as `(file_id, path)`
+    /// pairs) into a single fresh data file at `dest_path` tagged with
+    /// `dest_file_id`. Only keys currently pointing into one of `sources`
+    /// are touched — every other keydir entry (including the active file
+    /// being written to, if it's left out of `sources`) is untouched, so a
+    /// caller can safely merge just the closed files and keep writing to
+    /// the open one. The keydir already holds only the single most recent
+    /// location for each key (every write and recovery replay keeps it that
+    /// way), so survivorship falls out of simply copying whatever each live
+    /// key currently points at into the new file and repointing the keydir
+    /// there; tombstoned and superseded records are never visited and so are
+    /// dropped, and a live key whose record has since expired (see
+    /// [`KeyValue::is_expired`]) is dropped here too. A content-addressed
+    /// key (see [`BitcaskKeyFile::add_key_deduped`]) has its blob copied
+    /// only once no matter how many keys share it, and [`ContentStore`] is
+    /// updated to the blob's new location so later dedup lookups don't hand
+    /// out a pointer into a file this call is about to delete. The new file
+    /// is written under a temporary name and renamed into place so a crash
+    /// mid-merge leaves the old files intact.
+    pub fn merge(
+        &mut self,
+        sources: &[(u32, String)],
+        dest_path: &str,
+        dest_file_id: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_path = format!("{dest_path}.merging");
+        let mut dest_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+
+        let source_ids: Vec<u32> = sources.iter().map(|(id, _)| *id).collect();
+
+        let mut source_bytes_total = 0u64;
+        for (_, source_path) in sources {
+            source_bytes_total += std::fs::metadata(source_path)?.len();
+        }
+
+        let live_entries: Vec<(String, KeyMetadata)> = self
+            .key_map
+            .iter()
+            .filter(|(_, meta)| source_ids.contains(&meta.file_id))
+            .map(|(key, meta)| (key.clone(), *meta))
+            .collect();
+        let live_bytes_removed: u64 = live_entries.iter().map(|(_, meta)| meta.size).sum();
+
+        let mut relocated = HashMap::with_capacity(live_entries.len());
+        let mut relocated_blobs: HashMap<ValueHash, KeyMetadata> = HashMap::new();
+        let mut new_live_bytes = 0u64;
+
+        for (key, meta) in live_entries {
+            let (_, source_path) = sources
+                .iter()
+                .find(|(id, _)| *id == meta.file_id)
+                .expect("file_id was drawn from sources");
+
+            if let Some(hash) = self.key_hashes.get(&key).copied() {
+                let blob_meta = match relocated_blobs.get(&hash) {
+                    Some(blob_meta) => *blob_meta,
+                    None => {
+                        // `key` is live, so its blob must still be
+                        // referenced; a zero ref count here would mean
+                        // key_hashes and content_store have drifted apart.
+                        debug_assert!(
+                            self.content_store.ref_count(hash) > 0,
+                            "merge found a live key pointing at a dead blob"
+                        );
+
+                        let mut source_file = File::open(source_path)?;
+                        source_file.seek(SeekFrom::Start(meta.offset))?;
+                        let mut blob_buf = vec![0u8; meta.size as usize];
+                        source_file.read_exact(&mut blob_buf)?;
+
+                        let new_offset = dest_file.stream_position()?;
+                        dest_file.write_all(&blob_buf)?;
+
+                        let blob_meta = KeyMetadata {
+                            file_id: dest_file_id,
+                            offset: new_offset,
+                            size: meta.size,
+                        };
+                        relocated_blobs.insert(hash, blob_meta);
+                        blob_meta
+                    }
+                };
+
+                relocated.insert(key, blob_meta);
+                new_live_bytes += blob_meta.size;
+                continue;
+            }
+
+            let mut source_file = File::open(source_path)?;
+            source_file.seek(SeekFrom::Start(meta.offset))?;
+            let mut record_buf = vec![0u8; meta.size as usize];
+            source_file.read_exact(&mut record_buf)?;
+
+            // Records that don't parse as a plaintext `KeyValue` frame (e.g.
+            // an encrypted record) carry no TTL we can check here, so
+            // they're copied through unchanged rather than dropped.
+            let is_expired = KeyValue::deserialize(&record_buf)
+                .map(|record: KeyValue<Key, _>| record.is_expired(now_millis()))
+                .unwrap_or(false);
+            if is_expired {
+                continue;
+            }
+
+            let new_offset = dest_file.stream_position()?;
+            dest_file.write_all(&record_buf)?;
+
+            relocated.insert(
+                key,
+                KeyMetadata {
+                    file_id: dest_file_id,
+                    offset: new_offset,
+                    size: meta.size,
+                },
+            );
+            new_live_bytes += meta.size;
+        }
+
+        dest_file.flush()?;
+        drop(dest_file);
+        std::fs::rename(&tmp_path, dest_path)?;
+
+        for (_, source_path) in sources {
+            if source_path != dest_path {
+                std::fs::remove_file(source_path)?;
+            }
+        }
+
+        for (hash, blob_meta) in relocated_blobs {
+            self.content_store.relocate(
+                hash,
+                BlobLocation {
+                    file_id: blob_meta.file_id,
+                    offset: blob_meta.offset,
+                    size: blob_meta.size,
+                },
+            );
+        }
+
+        self.key_map.retain(|_, meta| !source_ids.contains(&meta.file_id));
+        self.key_map.extend(relocated);
+
+        self.live_bytes -= live_bytes_removed;
+        self.live_bytes += new_live_bytes;
+        let reclaimed_dead_bytes = source_bytes_total.saturating_sub(live_bytes_removed);
+        self.dead_bytes = self.dead_bytes.saturating_sub(reclaimed_dead_bytes);
+
+        Ok(())
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("error reading system time")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    /// Unique path under the OS temp dir for a scratch file this test owns.
+    fn temp_path(label: &str) -> String {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir()
+            .join(format!(
+                "bitcask_keyfile_test_{}_{label}_{n}",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_readd_after_load_does_not_underflow_live_bytes() {
+        let keydir_path = temp_path("keydir");
+
+        let mut key_file = BitcaskKeyFile::new(&keydir_path);
+        key_file.add_key("key".to_string(), 0, 0, 9);
+        key_file.save().unwrap();
+
+        // A fresh instance loading the saved hint file must recover
+        // live_bytes, not just key_map, so re-adding a loaded key behaves
+        // the same as it would have in the process that first wrote it.
+        let mut reloaded = BitcaskKeyFile::new(&keydir_path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.live_bytes, 9);
+        reloaded.add_key("key".to_string(), 0, 100, 12);
+
+        assert_eq!(reloaded.get_key_info("key").unwrap().offset, 100);
+        assert_eq!(reloaded.live_bytes, 12);
+
+        std::fs::remove_file(&keydir_path).ok();
+    }
+
+    #[test]
+    fn test_save_load_roundtrips_dedup_state_across_restarts() {
+        let keydir_path = temp_path("keydir");
+        let content_path = temp_path("content");
+
+        let mut key_file = BitcaskKeyFile::new(&keydir_path);
+        key_file
+            .add_key_deduped("a".to_string(), b"shared value", &content_path, 0)
+            .unwrap();
+        key_file.save().unwrap();
+
+        // A process restart only has the hint file to go on; content_store
+        // and key_hashes must have been persisted alongside key_map, or a
+        // later add_key_deduped for the same value appends a fresh copy
+        // instead of recognizing it.
+        let mut reloaded = BitcaskKeyFile::new(&keydir_path);
+        reloaded.load().unwrap();
+        reloaded
+            .add_key_deduped("b".to_string(), b"shared value", &content_path, 0)
+            .unwrap();
+
+        let a_info = *reloaded.get_key_info("a").unwrap();
+        let b_info = *reloaded.get_key_info("b").unwrap();
+        assert_eq!(a_info.offset, b_info.offset);
+        assert_eq!(
+            std::fs::metadata(&content_path).unwrap().len(),
+            b"shared value".len() as u64
+        );
+
+        std::fs::remove_file(&keydir_path).ok();
+        std::fs::remove_file(&content_path).ok();
+    }
+
+    #[test]
+    fn test_recover_propagates_error_on_malformed_non_tail_record() {
+        let data_path = temp_path("data");
+
+        let good_record = KeyValue::new(b"key".to_vec(), b"value".to_vec());
+        let good_bytes = KeyValue::serialize(&good_record).unwrap();
+
+        // A record-shaped blob with a CRC that doesn't match its payload:
+        // long enough that it isn't a truncated tail, but it will never
+        // deserialize successfully. This must surface as an error rather
+        // than being silently treated like a torn write.
+        let malformed: Vec<u8> = vec![
+            0x00, 0x00, 0x00, 0x00, // CRC (wrong)
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // timestamp
+            0x00, // flags
+            0x01, // key length = 1
+            0x00, // value length = 0
+            b'x', // key byte
+            0x00, // TLV field count = 0
+        ];
+
+        let mut buf = good_bytes;
+        buf.extend(&malformed);
+        std::fs::write(&data_path, &buf).unwrap();
+
+        let mut key_file = BitcaskKeyFile::new(&temp_path("keydir"));
+        let result = key_file.recover(&data_path, 0);
+
+        assert!(result.is_err());
+
+        std::fs::remove_file(&data_path).ok();
+    }
+
+    #[test]
+    fn test_recover_stops_silently_on_truncated_tail() {
+        let data_path = temp_path("data");
+
+        let good_record = KeyValue::new(b"key".to_vec(), b"value".to_vec());
+        let good_bytes = KeyValue::serialize(&good_record).unwrap();
+
+        // Fewer bytes than even the fixed header: the expected shape of a
+        // write that was in flight when the process crashed.
+        let mut buf = good_bytes;
+        buf.push(0x00);
+        std::fs::write(&data_path, &buf).unwrap();
+
+        let mut key_file = BitcaskKeyFile::new(&temp_path("keydir"));
+        key_file.recover(&data_path, 0).unwrap();
+
+        assert_eq!(key_file.get_key_info("key").unwrap().offset, 0);
+    }
+
+    #[test]
+    fn test_merge_leaves_keys_outside_sources_untouched() {
+        let closed_path = temp_path("closed");
+        let active_path = temp_path("active");
+        let dest_path = temp_path("dest");
+
+        let mut key_file = BitcaskKeyFile::new(&temp_path("keydir"));
+
+        // "old_key" lives in the closed file (file_id 0), which is merged;
+        // "active_key" lives in the still-open active file (file_id 1),
+        // which is left out of `sources` and must survive untouched.
+        let old_record = KeyValue::new(b"old_key".to_vec(), b"old_value".to_vec());
+        let old_bytes = KeyValue::serialize(&old_record).unwrap();
+        std::fs::write(&closed_path, &old_bytes).unwrap();
+        key_file.add_key("old_key".to_string(), 0, 0, old_bytes.len() as u64);
+
+        let active_record = KeyValue::new(b"active_key".to_vec(), b"active_value".to_vec());
+        let active_bytes = KeyValue::serialize(&active_record).unwrap();
+        std::fs::write(&active_path, &active_bytes).unwrap();
+        key_file.add_key("active_key".to_string(), 1, 0, active_bytes.len() as u64);
+
+        key_file
+            .merge(&[(0, closed_path.clone())], &dest_path, 2)
+            .unwrap();
+
+        assert_eq!(key_file.get_key_info("old_key").unwrap().file_id, 2);
+        let active_info = key_file.get_key_info("active_key").unwrap();
+        assert_eq!(active_info.file_id, 1);
+        assert_eq!(active_info.offset, 0);
+
+        std::fs::remove_file(&active_path).ok();
+        std::fs::remove_file(&dest_path).ok();
+    }
+
+    #[test]
+    fn test_merge_preserves_dedup_sharing_a_single_blob() {
+        let content_path = temp_path("content");
+        let dest_path = temp_path("dest");
+
+        let mut key_file = BitcaskKeyFile::new(&temp_path("keydir"));
+
+        key_file
+            .add_key_deduped("a".to_string(), b"shared value", &content_path, 0)
+            .unwrap();
+        key_file
+            .add_key_deduped("b".to_string(), b"shared value", &content_path, 0)
+            .unwrap();
+
+        let content_len_before = std::fs::metadata(&content_path).unwrap().len();
+        assert_eq!(content_len_before, b"shared value".len() as u64);
+
+        key_file
+            .merge(&[(0, content_path.clone())], &dest_path, 1)
+            .unwrap();
+
+        // The blob must still be stored exactly once after compaction.
+        let dest_len = std::fs::metadata(&dest_path).unwrap().len();
+        assert_eq!(dest_len, b"shared value".len() as u64);
+
+        let a_info = *key_file.get_key_info("a").unwrap();
+        let b_info = *key_file.get_key_info("b").unwrap();
+        assert_eq!(a_info.file_id, 1);
+        assert_eq!(a_info.file_id, b_info.file_id);
+        assert_eq!(a_info.offset, b_info.offset);
+
+        std::fs::remove_file(&dest_path).ok();
+    }
+
+    #[test]
+    fn test_add_key_deduped_after_merge_reuses_relocated_blob() {
+        let content_path = temp_path("content");
+        let dest_path = temp_path("dest");
+
+        let mut key_file = BitcaskKeyFile::new(&temp_path("keydir"));
+        key_file
+            .add_key_deduped("a".to_string(), b"shared value", &content_path, 0)
+            .unwrap();
+
+        key_file
+            .merge(&[(0, content_path.clone())], &dest_path, 1)
+            .unwrap();
+
+        // Writing a second key with the same value after the merge must
+        // point at the relocated blob, not the pre-merge (now-deleted) file.
+        key_file
+            .add_key_deduped("b".to_string(), b"shared value", &dest_path, 1)
+            .unwrap();
+
+        let a_info = *key_file.get_key_info("a").unwrap();
+        let b_info = *key_file.get_key_info("b").unwrap();
+        assert_eq!(b_info.file_id, a_info.file_id);
+        assert_eq!(b_info.offset, a_info.offset);
+        assert_eq!(
+            std::fs::metadata(&dest_path).unwrap().len(),
+            b"shared value".len() as u64
+        );
+
+        std::fs::remove_file(&dest_path).ok();
     }
 }
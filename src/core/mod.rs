@@ -0,0 +1,4 @@
+pub mod content_store;
+pub mod encryption;
+pub mod keyfile;
+pub mod serdes;
@@ -1,7 +1,8 @@
-use std::convert::TryInto;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+use crate::core::encryption::{Cipher, NONCE_LEN};
+
 pub type Key = Vec<u8>;
 pub type Value = Vec<u8>;
 
@@ -10,28 +11,63 @@ pub struct KeyValue<K, V> {
     pub key: K,
     pub value: V,
     pub timestamp: Vec<u8>,
+    /// Marks this record as a delete (tombstone) rather than a live value.
+    /// The value is always empty for a tombstone.
+    pub is_tombstone: bool,
+    /// Expiry time in milliseconds since the epoch, carried in the record's
+    /// TLV trailer. `None` means the record never expires.
+    pub expires_at_millis: Option<u64>,
 }
 
 impl KeyValue<Key, Value> {
     pub fn new<K: Into<Key>, V: Into<Value>>(key: K, value: V) -> Self {
-        let millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("error reading system time")
-            .as_millis();
-
-        let millis_bytes = millis.to_be_bytes();
-        let mut timestamp = vec![0; 8];
+        Self {
+            key: key.into(),
+            value: value.into(),
+            timestamp: now_millis_be(),
+            is_tombstone: false,
+            expires_at_millis: None,
+        }
+    }
 
-        // Copy millis_bytes into the last bytes of the timestamp vector
-        let start_idx = timestamp.len().saturating_sub(millis_bytes.len());
-        timestamp[start_idx..].copy_from_slice(&millis_bytes[8..]);
+    /// Like [`KeyValue::new`], but the record carries an expiry of `ttl`
+    /// from now. Recovery and merge scans treat an expired record as dead,
+    /// the same way they treat a tombstone.
+    pub fn new_with_ttl<K: Into<Key>, V: Into<Value>>(key: K, value: V, ttl: Duration) -> Self {
+        Self {
+            expires_at_millis: Some(now_millis() + ttl.as_millis() as u64),
+            ..Self::new(key, value)
+        }
+    }
 
+    /// Builds a tombstone record for `key`: a delete marker with no value
+    /// that, once appended, shadows every earlier record for that key.
+    pub fn new_tombstone<K: Into<Key>>(key: K) -> Self {
         Self {
             key: key.into(),
-            value: value.into(),
-            timestamp,
+            value: Value::new(),
+            timestamp: now_millis_be(),
+            is_tombstone: true,
+            expires_at_millis: None,
         }
     }
+
+    /// True if this record carries an expiry that is at or before `now_millis`.
+    pub fn is_expired(&self, now_millis: u64) -> bool {
+        matches!(self.expires_at_millis, Some(expiry) if expiry <= now_millis)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("error reading system time")
+        .as_millis() as u64
+}
+
+fn now_millis_be() -> Vec<u8> {
+    let millis_bytes = now_millis().to_be_bytes();
+    millis_bytes.to_vec()
 }
 
 pub trait Serdes<T> {
@@ -42,10 +78,44 @@ pub trait Serdes<T> {
     fn serialize(a: &T) -> Result<Vec<u8>, Self::SerializeErr>;
 }
 
+/// Distinguishes a cleanly truncated input (the expected shape of a torn
+/// write at a crash site) from one that had enough bytes but didn't decode
+/// into a valid record. [`BitcaskKeyFile::recover`] relies on this: it's
+/// safe to stop scanning and drop the remainder on a `Truncated` error, but
+/// a `Malformed` error partway through a file (e.g. a record whose CRC does
+/// not match its key/value) means something is actually wrong and
+/// shouldn't be swallowed the same way. The kind is decided from the bytes
+/// alone: a record in some other format (such as an encrypted one) can be
+/// reported as either kind, depending on what its bytes decode to.
+///
+/// [`BitcaskKeyFile::recover`]: crate::core::keyfile::BitcaskKeyFile::recover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeserializeErrorKind {
+    Truncated,
+    Malformed,
+}
+
 #[derive(Debug, Error)]
 #[error("DeserializeError: {message}")]
 pub struct DeserializeError {
     pub message: String,
+    pub kind: DeserializeErrorKind,
+}
+
+impl DeserializeError {
+    fn truncated(message: &str) -> Self {
+        DeserializeError {
+            message: message.to_string(),
+            kind: DeserializeErrorKind::Truncated,
+        }
+    }
+
+    fn malformed(message: &str) -> Self {
+        DeserializeError {
+            message: message.to_string(),
+            kind: DeserializeErrorKind::Malformed,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -67,11 +137,11 @@ impl Serdes<KeyValue<Key, Value>> for KeyValue<Key, Value> {
                 key: parsed_bytes.key,
                 value: parsed_bytes.value,
                 timestamp,
+                is_tombstone: parsed_bytes.is_tombstone,
+                expires_at_millis: parsed_bytes.expires_at_millis,
             })
         } else {
-            Err(DeserializeError {
-                message: String::from("Invalid CRC32 checksum"),
-            })
+            Err(DeserializeError::malformed("Invalid CRC32 checksum"))
         }
     }
 
@@ -79,14 +149,153 @@ impl Serdes<KeyValue<Key, Value>> for KeyValue<Key, Value> {
         let mut buff = Vec::new();
         buff.extend(calculate_crc(&a.key, &a.value));
         buff.extend(&a.timestamp);
-        buff.extend(&(a.key.len() as u16).to_be_bytes());
-        buff.extend(&(a.value.len() as u16).to_be_bytes());
+        buff.push(if a.is_tombstone {
+            TOMBSTONE_FLAG
+        } else {
+            0x00
+        });
+        buff.extend(encode_varint(a.key.len()));
+        buff.extend(encode_varint(a.value.len()));
         buff.extend(a.key.iter());
         buff.extend(a.value.iter());
+        buff.extend(encode_tlvs(a));
         Ok(buff)
     }
 }
 
+/// Flag-byte bit set on a record's header to mark it as a tombstone (a
+/// delete marker) rather than a live value.
+const TOMBSTONE_FLAG: u8 = 0x01;
+
+/// TLV type for the expiry (TTL) field: an 8-byte big-endian milliseconds-
+/// since-epoch timestamp. Even type ids are "must understand" — a reader
+/// that doesn't recognize one has to treat the record as unreadable rather
+/// than silently ignore a field that changes its meaning.
+const TLV_TYPE_EXPIRY: usize = 0;
+
+/// Builds the TLV trailer appended after the key/value bytes: a varint
+/// count followed by that many `[type][length][value]` triples in strictly
+/// ascending type order. Unknown even types are errors for a reader that
+/// doesn't understand them; unknown odd types are safe to skip — this is
+/// what lets old and new readers interoperate as fields are added.
+fn encode_tlvs(a: &KeyValue<Key, Value>) -> Vec<u8> {
+    let mut fields: Vec<(usize, Vec<u8>)> = Vec::new();
+    if let Some(expiry) = a.expires_at_millis {
+        fields.push((TLV_TYPE_EXPIRY, expiry.to_be_bytes().to_vec()));
+    }
+    fields.sort_by_key(|(ty, _)| *ty);
+
+    let mut buf = encode_varint(fields.len());
+    for (ty, value) in fields {
+        buf.extend(encode_varint(ty));
+        buf.extend(encode_varint(value.len()));
+        buf.extend(value);
+    }
+    buf
+}
+
+/// Inverse of [`encode_tlvs`]. Returns the decoded expiry (if present) and
+/// the number of bytes consumed from the start of `input`.
+fn decode_tlvs(input: &[u8]) -> Result<(Option<u64>, usize), DeserializeError> {
+    let (field_count, mut cursor) = decode_varint(input)?;
+
+    let mut expires_at_millis = None;
+    let mut last_type: Option<usize> = None;
+
+    for _ in 0..field_count {
+        let (ty, ty_size) = decode_varint(&input[cursor..])?;
+        cursor = checked_add(cursor, ty_size)?;
+        let (length, length_size) = decode_varint(&input[cursor..])?;
+        cursor = checked_add(cursor, length_size)?;
+
+        if last_type.is_some_and(|last| ty <= last) {
+            return Err(DeserializeError::malformed(
+                "TLV field types must be strictly ascending",
+            ));
+        }
+        last_type = Some(ty);
+
+        let value_end = checked_add(cursor, length)?;
+        if value_end > input.len() {
+            return Err(DeserializeError::truncated("Input too short"));
+        }
+        let value = &input[cursor..value_end];
+
+        match ty {
+            TLV_TYPE_EXPIRY => {
+                let bytes: [u8; 8] = value
+                    .try_into()
+                    .map_err(|_| DeserializeError::malformed("Malformed TTL field"))?;
+                expires_at_millis = Some(u64::from_be_bytes(bytes));
+            }
+            unknown if unknown % 2 == 0 => {
+                return Err(DeserializeError::malformed(&format!(
+                    "Unknown required TLV field type {unknown}"
+                )));
+            }
+            _ => {
+                // Unknown optional field: safe to skip.
+            }
+        }
+
+        cursor = value_end;
+    }
+
+    Ok((expires_at_millis, cursor))
+}
+
+/// Maximum number of bytes a varint-encoded `usize` can occupy on this
+/// platform (ceil(usize::BITS / 7)), used to reject overlong/malformed
+/// continuation sequences instead of looping forever on corrupt input.
+const MAX_VARINT_BYTES: usize = (usize::BITS as usize).div_ceil(7);
+
+/// Encodes `value` as a LEB128-style varint: the low 7 bits of each byte
+/// hold payload, and the high bit (0x80) is set while more bytes follow.
+fn encode_varint(mut value: usize) -> Vec<u8> {
+    let mut buf = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            buf.push(byte | 0x80);
+        } else {
+            buf.push(byte);
+            break;
+        }
+    }
+    buf
+}
+
+/// Decodes a varint from the start of `input`, returning the decoded value
+/// and the number of bytes consumed. Errors on a continuation sequence that
+/// runs past `MAX_VARINT_BYTES` or off the end of `input`.
+fn decode_varint(input: &[u8]) -> Result<(usize, usize), DeserializeError> {
+    let mut result: usize = 0;
+
+    for (i, &byte) in input.iter().enumerate() {
+        if i >= MAX_VARINT_BYTES {
+            return Err(DeserializeError::malformed("Varint is too long"));
+        }
+
+        result |= ((byte & 0x7f) as usize) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            return Ok((result, i + 1));
+        }
+    }
+
+    Err(DeserializeError::truncated("Truncated varint"))
+}
+
+/// Adds `a` and `b`, failing cleanly instead of panicking on overflow. Every
+/// offset/length derived from a decoded varint must go through this before
+/// being used in further arithmetic, since a malformed or adversarial input
+/// can decode a single varint as large as `usize::MAX`.
+fn checked_add(a: usize, b: usize) -> Result<usize, DeserializeError> {
+    a.checked_add(b)
+        .ok_or_else(|| DeserializeError::malformed("Length field overflows"))
+}
+
 #[derive(Debug, PartialEq, Clone)]
 struct ParsedBytes {
     crc_bytes: Vec<u8>,
@@ -95,40 +304,42 @@ struct ParsedBytes {
     value_length: usize,
     key: Key,
     value: Value,
+    is_tombstone: bool,
+    expires_at_millis: Option<u64>,
+    /// Total number of bytes this record occupies in its backing buffer.
+    consumed: usize,
 }
 
+/// Size of the fixed portion of the record header: a 4-byte CRC, an 8-byte
+/// timestamp, and a 1-byte flags field. The key/value lengths that follow
+/// are varint-encoded and so have no fixed size.
+const FIXED_HEADER_LEN: usize = 13;
+
 fn parse_input(input: &[u8]) -> Result<ParsedBytes, DeserializeError> {
-    if input.len() < 16 {
-        return Err(DeserializeError {
-            message: String::from("Input too short"),
-        });
+    if input.len() < FIXED_HEADER_LEN {
+        return Err(DeserializeError::truncated("Input too short"));
     }
 
     let crc_bytes = input[0..4].to_vec();
     let timestamp_bytes = input[4..12].to_vec();
+    let is_tombstone = input[12] & TOMBSTONE_FLAG != 0;
 
-    let key_length = input[12..14]
-        .try_into()
-        .map_err(|_| DeserializeError {
-            message: String::from("Failed to parse key length"),
-        })
-        .map(|bytes: [u8; 2]| u16::from_be_bytes(bytes) as usize)?;
-
-    let value_length = input[14..16]
-        .try_into()
-        .map_err(|_| DeserializeError {
-            message: String::from("Failed to parse value length"),
-        })
-        .map(|bytes: [u8; 2]| u16::from_be_bytes(bytes) as usize)?;
-
-    if input.len() < 16 + key_length + value_length {
-        return Err(DeserializeError {
-            message: String::from("Input too short"),
-        });
+    let (key_length, key_length_size) = decode_varint(&input[FIXED_HEADER_LEN..])?;
+    let value_start = checked_add(FIXED_HEADER_LEN, key_length_size)?;
+    let (value_length, value_length_size) = decode_varint(&input[value_start..])?;
+
+    let data_start = checked_add(value_start, value_length_size)?;
+    let key_end = checked_add(data_start, key_length)?;
+    let value_end = checked_add(key_end, value_length)?;
+    if input.len() < value_end {
+        return Err(DeserializeError::truncated("Input too short"));
     }
 
-    let key = input[16..16 + key_length].to_vec();
-    let value = input[16 + key_length..16 + key_length + value_length].to_vec();
+    let key = input[data_start..key_end].to_vec();
+    let value = input[key_end..value_end].to_vec();
+
+    let tlv_start = value_end;
+    let (expires_at_millis, tlv_len) = decode_tlvs(&input[tlv_start..])?;
 
     Ok(ParsedBytes {
         crc_bytes,
@@ -137,9 +348,19 @@ fn parse_input(input: &[u8]) -> Result<ParsedBytes, DeserializeError> {
         value_length,
         key,
         value,
+        is_tombstone,
+        expires_at_millis,
+        consumed: checked_add(tlv_start, tlv_len)?,
     })
 }
 
+/// Returns the total number of bytes the record at the start of `input`
+/// occupies (header + key + value + TLV trailer). Used by recovery to walk a data file
+/// record-by-record without re-deriving offsets by hand.
+pub fn record_len(input: &[u8]) -> Result<usize, DeserializeError> {
+    Ok(parse_input(input)?.consumed)
+}
+
 fn calculate_crc(key: &[u8], value: &[u8]) -> [u8; 4] {
     let mut hasher = crc32fast::Hasher::new();
     hasher.update(key);
@@ -148,6 +369,105 @@ fn calculate_crc(key: &[u8], value: &[u8]) -> [u8; 4] {
     crc_value.to_be_bytes()
 }
 
+/// Both supported AEAD ciphers append a 16-byte authentication tag.
+const AEAD_TAG_LEN: usize = 16;
+
+/// Fixed, unencrypted header for an encrypted record: an 8-byte timestamp
+/// followed by the flags byte. There is deliberately no CRC field here —
+/// see [`serialize_encrypted`].
+const ENCRYPTED_HEADER_LEN: usize = 9;
+
+/// Serializes `a` like [`Serdes::serialize`], but encrypts the key+value
+/// payload with `cipher` first. The record layout becomes
+/// `[timestamp][flags][nonce][key length][value length][ciphertext-with-tag][TLV trailer]`.
+/// Unlike the plaintext layout, there's no separate CRC32 field: the AEAD
+/// tag already authenticates the ciphertext, and a CRC computed over the
+/// plaintext key/value (as the plaintext format does) needs no key to
+/// verify, so storing it alongside the ciphertext would let anyone with
+/// read access to the data file brute-force a low-entropy secret entirely
+/// offline. The TLV trailer is unencrypted, same as the plaintext format.
+pub fn serialize_encrypted(
+    a: &KeyValue<Key, Value>,
+    cipher: &Cipher,
+) -> Result<Vec<u8>, SerializeError> {
+    let mut plaintext = Vec::with_capacity(a.key.len() + a.value.len());
+    plaintext.extend(&a.key);
+    plaintext.extend(&a.value);
+
+    let (nonce, ciphertext) = cipher.encrypt(&plaintext).map_err(|e| SerializeError {
+        message: e.to_string(),
+    })?;
+
+    let mut buff = Vec::new();
+    buff.extend(&a.timestamp);
+    buff.push(if a.is_tombstone {
+        TOMBSTONE_FLAG
+    } else {
+        0x00
+    });
+    buff.extend(&nonce);
+    buff.extend(encode_varint(a.key.len()));
+    buff.extend(encode_varint(a.value.len()));
+    buff.extend(ciphertext);
+    buff.extend(encode_tlvs(a));
+    Ok(buff)
+}
+
+/// Inverse of [`serialize_encrypted`]: decrypts the record's ciphertext with
+/// `cipher` before splitting it back into key/value. The AEAD tag rejects
+/// any tampering or wrong-key attempt, so there's no separate checksum to
+/// validate here.
+pub fn deserialize_encrypted(
+    input: &[u8],
+    cipher: &Cipher,
+) -> Result<KeyValue<Key, Value>, DeserializeError> {
+    if input.len() < ENCRYPTED_HEADER_LEN + NONCE_LEN {
+        return Err(DeserializeError::truncated("Input too short"));
+    }
+
+    let timestamp = input[0..8].to_vec();
+    let is_tombstone = input[8] & TOMBSTONE_FLAG != 0;
+    let nonce = &input[ENCRYPTED_HEADER_LEN..ENCRYPTED_HEADER_LEN + NONCE_LEN];
+
+    let lengths_start = ENCRYPTED_HEADER_LEN + NONCE_LEN;
+    let (key_length, key_length_size) = decode_varint(&input[lengths_start..])?;
+    let value_start = checked_add(lengths_start, key_length_size)?;
+    let (value_length, value_length_size) = decode_varint(&input[value_start..])?;
+    let ciphertext_start = checked_add(value_start, value_length_size)?;
+    let payload_len = checked_add(key_length, value_length)?;
+    let ciphertext_len = checked_add(payload_len, AEAD_TAG_LEN)?;
+    let ciphertext_end = checked_add(ciphertext_start, ciphertext_len)?;
+
+    if input.len() < ciphertext_end {
+        return Err(DeserializeError::truncated("Input too short"));
+    }
+
+    let ciphertext = &input[ciphertext_start..ciphertext_end];
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|e| DeserializeError::malformed(&e.to_string()))?;
+
+    if plaintext.len() != payload_len {
+        return Err(DeserializeError::malformed(
+            "Decrypted payload length mismatch",
+        ));
+    }
+
+    let key = plaintext[..key_length].to_vec();
+    let value = plaintext[key_length..].to_vec();
+
+    let tlv_start = ciphertext_end;
+    let (expires_at_millis, _) = decode_tlvs(&input[tlv_start..])?;
+
+    Ok(KeyValue {
+        key,
+        value,
+        timestamp,
+        is_tombstone,
+        expires_at_millis,
+    })
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -157,7 +477,7 @@ mod tests {
     fn test_parse_input_success() {
         let input = vec![
             0x0D, 0x4A, 0x11, 0x85, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05,
-            0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x77, 0x6F, 0x72, 0x6C, 0x64,
+            0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x77, 0x6F, 0x72, 0x6C, 0x64, 0x00,
         ];
         let expected = ParsedBytes {
             crc_bytes: vec![0x0D, 0x4A, 0x11, 0x85],
@@ -166,6 +486,9 @@ mod tests {
             value_length: 5,
             key: b"hello".to_vec(),
             value: b"world".to_vec(),
+            is_tombstone: false,
+            expires_at_millis: None,
+            consumed: input.len(),
         };
         assert_eq!(parse_input(&input).unwrap(), expected);
     }
@@ -178,12 +501,116 @@ mod tests {
         assert_eq!(result.unwrap_err().message, "Input too short".to_string());
     }
 
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0usize, 1, 127, 128, 300, 16384, usize::MAX] {
+            let encoded = encode_varint(value);
+            let (decoded, consumed) = decode_varint(&encoded).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(consumed, encoded.len());
+        }
+    }
+
+    #[test]
+    fn test_decode_varint_fails_on_truncated_sequence() {
+        // High bit set with nothing following.
+        let input = vec![0x80];
+        let result = decode_varint(&input);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().message, "Truncated varint".to_string());
+    }
+
+    #[test]
+    fn test_parse_input_fails_cleanly_on_overlong_length() {
+        let mut input = vec![0u8; FIXED_HEADER_LEN];
+        input.extend(encode_varint(usize::MAX)); // key length
+        input.extend(encode_varint(0)); // value length
+
+        // Must return an error, not panic on overflowing arithmetic.
+        assert!(parse_input(&input).is_err());
+    }
+
+    #[test]
+    fn test_record_len_matches_serialized_length() {
+        let kv = KeyValue::new(b"hello".to_vec(), b"world".to_vec());
+        let serialized = KeyValue::serialize(&kv).unwrap();
+
+        assert_eq!(record_len(&serialized).unwrap(), serialized.len());
+    }
+
+    #[test]
+    fn test_serialize_deserialize_value_over_u16_limit() {
+        let big_value = vec![0x42; 100_000];
+        let kv = KeyValue::new(b"big".to_vec(), big_value.clone());
+
+        let serialized = KeyValue::serialize(&kv).unwrap();
+        let deserialized = KeyValue::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized.value, big_value);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_encrypted() {
+        use crate::core::encryption::{EncryptionHeader, EncryptionType};
+
+        let header = EncryptionHeader::new(EncryptionType::AesGcm);
+        let cipher = Cipher::new(EncryptionType::AesGcm, header.derive_key("hunter2").unwrap());
+
+        let kv = KeyValue::new(b"secret_key".to_vec(), b"secret_value".to_vec());
+
+        let serialized = serialize_encrypted(&kv, &cipher).unwrap();
+        let deserialized = deserialize_encrypted(&serialized, &cipher).unwrap();
+
+        assert_eq!(deserialized, kv);
+    }
+
+    #[test]
+    fn test_deserialize_encrypted_fails_with_wrong_key() {
+        use crate::core::encryption::{EncryptionHeader, EncryptionType};
+
+        let header = EncryptionHeader::new(EncryptionType::ChaCha20Poly1305);
+        let cipher = Cipher::new(
+            EncryptionType::ChaCha20Poly1305,
+            header.derive_key("correct").unwrap(),
+        );
+        let wrong_cipher = Cipher::new(
+            EncryptionType::ChaCha20Poly1305,
+            header.derive_key("wrong").unwrap(),
+        );
+
+        let kv = KeyValue::new(b"secret_key".to_vec(), b"secret_value".to_vec());
+        let serialized = serialize_encrypted(&kv, &cipher).unwrap();
+
+        assert!(deserialize_encrypted(&serialized, &wrong_cipher).is_err());
+    }
+
+    #[test]
+    fn test_serialize_encrypted_does_not_leak_a_keyless_checksum() {
+        use crate::core::encryption::{EncryptionHeader, EncryptionType};
+
+        // A CRC32 of the plaintext key/value needs no key to compute, so
+        // storing one next to the ciphertext would let an attacker brute
+        // force a low-entropy secret offline without ever touching the
+        // cipher. Confirm no prefix of the serialized record matches the
+        // plaintext CRC.
+        let header = EncryptionHeader::new(EncryptionType::AesGcm);
+        let cipher = Cipher::new(EncryptionType::AesGcm, header.derive_key("hunter2").unwrap());
+        let kv = KeyValue::new(b"secret_key".to_vec(), b"hunter2".to_vec());
+
+        let serialized = serialize_encrypted(&kv, &cipher).unwrap();
+        let plaintext_crc = calculate_crc(&kv.key, &kv.value);
+
+        assert_ne!(&serialized[0..4], &plaintext_crc[..]);
+    }
+
     #[test]
     fn test_serialize_deserialize() {
         let kv = KeyValue {
             key: b"hello".to_vec(),
             value: b"world".to_vec(),
             timestamp: vec![0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68],
+            is_tombstone: false,
+            expires_at_millis: None,
         };
 
         let serialized = KeyValue::serialize(&kv).unwrap();
@@ -192,6 +619,19 @@ mod tests {
         assert_eq!(deserialized, kv);
     }
 
+    #[test]
+    fn test_tombstone_roundtrip() {
+        let kv = KeyValue::new_tombstone(b"deleted_key".to_vec());
+        assert!(kv.is_tombstone);
+        assert!(kv.value.is_empty());
+
+        let serialized = KeyValue::serialize(&kv).unwrap();
+        let deserialized = KeyValue::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized, kv);
+        assert!(deserialized.is_tombstone);
+    }
+
     #[test]
     fn test_key_value_new() {
         let key = b"test_key".to_vec();
@@ -208,4 +648,45 @@ mod tests {
 
         assert_eq!(deserialized, kv);
     }
+
+    #[test]
+    fn test_ttl_roundtrip() {
+        let kv =
+            KeyValue::new_with_ttl(b"session".to_vec(), b"token".to_vec(), Duration::from_secs(60));
+
+        let serialized = KeyValue::serialize(&kv).unwrap();
+        let deserialized = KeyValue::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized, kv);
+        assert!(!deserialized.is_expired(now_millis()));
+        assert!(deserialized.is_expired(now_millis() + Duration::from_secs(61).as_millis() as u64));
+    }
+
+    #[test]
+    fn test_no_ttl_never_expires() {
+        let kv = KeyValue::new(b"forever".to_vec(), b"value".to_vec());
+        assert!(!kv.is_expired(u64::MAX));
+    }
+
+    #[test]
+    fn test_unknown_even_tlv_type_is_rejected() {
+        let mut input = encode_varint(1);
+        input.extend(encode_varint(2)); // unknown required type
+        input.extend(encode_varint(0)); // zero-length value
+
+        let result = decode_tlvs(&input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unknown_odd_tlv_type_is_skipped() {
+        let mut input = encode_varint(1);
+        input.extend(encode_varint(1)); // unknown optional type
+        input.extend(encode_varint(3)); // length
+        input.extend([0x01, 0x02, 0x03]);
+
+        let (expires_at_millis, consumed) = decode_tlvs(&input).unwrap();
+        assert_eq!(expires_at_millis, None);
+        assert_eq!(consumed, input.len());
+    }
 }
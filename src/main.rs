@@ -1,19 +1,37 @@
 use std::io::Write;
 use std::io::{Seek, SeekFrom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 mod core;
 
 use std::fs::OpenOptions;
 
-use crate::core::serdes::{KeyValue, Serdes};
+use crate::core::encryption::{Cipher, EncryptionHeader, EncryptionType, SALT_LEN};
+use crate::core::keyfile::{BitcaskKeyFile, DEFAULT_MERGE_THRESHOLD};
+use crate::core::serdes::{deserialize_encrypted, serialize_encrypted, KeyValue, Serdes};
+
+const FILE_ID: u32 = 0;
+const MERGED_FILE_ID: u32 = 1;
+const CONTENT_FILE_ID: u32 = 2;
+const TTL_FILE_ID: u32 = 3;
 
 fn main() -> std::io::Result<()> {
     let kv = KeyValue::new(b"test_key2".to_vec(), b"test_value".to_vec());
     let serialized: Vec<u8> = KeyValue::serialize(&kv).unwrap();
 
     let data_file_path = "data.bin";
+    let key_file_path = "data.keydir";
 
-    // todo: add key file updating here
+    let mut key_file = BitcaskKeyFile::new(key_file_path);
+    key_file
+        .load()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    // The hint file is a pure optimization: rebuild from the data file too,
+    // so a missing/stale hint file never loses an entry written after it
+    // was last saved.
+    key_file
+        .recover(data_file_path, FILE_ID)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
 
     // Append serialized data to the data file and store the offset in the HashMap
     let mut data_file = OpenOptions::new()
@@ -22,8 +40,122 @@ fn main() -> std::io::Result<()> {
         .read(true)
         .open(data_file_path)?;
 
-    let _offset = data_file.seek(SeekFrom::End(0))?;
+    let offset = data_file.seek(SeekFrom::End(0))?;
     data_file.write_all(&serialized)?;
 
+    key_file.add_key(
+        String::from_utf8_lossy(&kv.key).into_owned(),
+        FILE_ID,
+        offset,
+        serialized.len() as u64,
+    );
+
+    // Demonstrate a tombstone delete, then compact away the dead bytes it
+    // (and any prior overwrite) leaves behind once the file crosses
+    // DEFAULT_MERGE_THRESHOLD.
+    key_file
+        .remove_key("test_key2", data_file_path)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if key_file.needs_merge(DEFAULT_MERGE_THRESHOLD) {
+        let merged_file_path = "data.merged.bin";
+        key_file
+            .merge(
+                &[(FILE_ID, data_file_path.to_string())],
+                merged_file_path,
+                MERGED_FILE_ID,
+            )
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    }
+
+    // Demonstrate at-rest encryption: a passphrase-derived AEAD cipher
+    // protects a record written to its own encrypted data file, with the
+    // header holding everything needed to re-derive the key on reopen.
+    let encrypted_file_path = "data.enc.bin";
+    let header = EncryptionHeader::new(EncryptionType::ChaCha20Poly1305);
+    let key = header
+        .derive_key("changeme")
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let cipher = Cipher::new(EncryptionType::ChaCha20Poly1305, key);
+
+    let secret = KeyValue::new(b"api_token".to_vec(), b"super secret value".to_vec());
+    let encrypted = serialize_encrypted(&secret, &cipher)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let mut encrypted_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(encrypted_file_path)?;
+    encrypted_file.write_all(&header.to_bytes())?;
+    encrypted_file.write_all(&encrypted)?;
+    drop(encrypted_file);
+
+    let stored = std::fs::read(encrypted_file_path)?;
+    let header_len = 1 + SALT_LEN + 12;
+    let stored_header = EncryptionHeader::from_bytes(&stored[..header_len])
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let stored_key = stored_header
+        .derive_key("changeme")
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let stored_cipher = Cipher::new(stored_header.algorithm, stored_key);
+    let decrypted = deserialize_encrypted(&stored[header_len..], &stored_cipher)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    assert_eq!(decrypted.value, secret.value);
+
+    // Demonstrate content-addressed storage: two keys that happen to share
+    // a value are pointed at a single stored blob instead of each storing
+    // their own copy.
+    let content_file_path = "data.content.bin";
+    key_file
+        .add_key_deduped(
+            "profile_a".to_string(),
+            b"shared avatar bytes",
+            content_file_path,
+            CONTENT_FILE_ID,
+        )
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    key_file
+        .add_key_deduped(
+            "profile_b".to_string(),
+            b"shared avatar bytes",
+            content_file_path,
+            CONTENT_FILE_ID,
+        )
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // Demonstrate a TTL record: it round-trips through the same plaintext
+    // format as any other record, but carries an expiry in its TLV trailer
+    // that a later recover/merge pass treats as dead once it passes.
+    let ttl_file_path = "data.ttl.bin";
+    let session = KeyValue::new_with_ttl(
+        b"session_token".to_vec(),
+        b"short_lived_value".to_vec(),
+        Duration::from_secs(60),
+    );
+    let serialized_session = KeyValue::serialize(&session).unwrap();
+
+    let mut ttl_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(ttl_file_path)?;
+    let session_offset = ttl_file.seek(SeekFrom::End(0))?;
+    ttl_file.write_all(&serialized_session)?;
+
+    key_file.add_key(
+        String::from_utf8_lossy(&session.key).into_owned(),
+        TTL_FILE_ID,
+        session_offset,
+        serialized_session.len() as u64,
+    );
+    let now_millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("error reading system time")
+        .as_millis() as u64;
+    assert!(!session.is_expired(now_millis));
+
+    key_file
+        .save()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
     Ok(())
 }